@@ -1,33 +1,52 @@
 use anyhow::bail;
 use std::ffi::CString;
-use std::path::Path;
+use std::os::raw::{c_int, c_void};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::thread::{spawn, JoinHandle};
 use std::time::{Instant, SystemTime};
 
 use crossbeam::channel::{Receiver, Sender};
 use derive_builder::Builder;
+use ffmpeg::util::frame::audio::Audio;
 use ffmpeg::util::frame::video::Video;
 use ffmpeg_next as ffmpeg;
 use ffmpeg_next::codec::{Id, Parameters};
-use ffmpeg_next::ffi::{av_bsf_alloc, av_bsf_init, AVBSFContext, AVERROR, AVERROR_EOF};
+use ffmpeg_next::ffi::{
+    av_bsf_alloc, av_bsf_init, AVBSFContext, AVERROR, AVERROR_EOF, AV_NOPTS_VALUE,
+};
+use ffmpeg_next::format::context::input::Input;
 use ffmpeg_next::format::{input_with_dictionary, Pixel};
 use ffmpeg_next::log::Level;
 use ffmpeg_next::packet::Mut;
-use ffmpeg_next::software::converter;
 use ffmpeg_next::sys::{
-    av_bsf_get_by_name, av_bsf_receive_packet, av_bsf_send_packet, av_opt_set,
-    avcodec_parameters_copy, AV_OPT_SEARCH_CHILDREN, EAGAIN,
+    av_audio_fifo_alloc, av_audio_fifo_free, av_audio_fifo_read, av_audio_fifo_size,
+    av_audio_fifo_write, av_bsf_get_by_name, av_bsf_receive_packet, av_bsf_send_packet, av_free,
+    av_interleaved_write_frame, av_malloc, av_opt_set, av_rescale_q, av_write_trailer,
+    avcodec_parameters_copy, avformat_alloc_context, avformat_alloc_output_context2,
+    avformat_find_stream_info, avformat_free_context, avformat_new_stream, avformat_open_input,
+    avformat_write_header, avio_alloc_context, avio_closep, avio_context_free, avio_open,
+    AVAudioFifo, AVFormatContext, AVIOContext, AVIO_FLAG_WRITE, AV_OPT_SEARCH_CHILDREN, EAGAIN,
 };
 use ffmpeg_next::{Packet, Rational};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use parking_lot::Mutex;
 use pyo3::exceptions::{PyBrokenPipeError, PySystemError, PyValueError};
 use pyo3::prelude::*;
 use pyo3::types::PyBytes;
 
-const DECODING_FORMAT: Pixel = Pixel::RGB24;
-const DECODED_PIX_BYTES: u32 = 3;
+/// Number of samples per channel delivered in each decoded `AudioFrameEnvelope`.
+/// Decoders are free to hand back arbitrarily sized frames; we repack them
+/// through an FFmpeg audio FIFO so consumers always see this fixed frame size.
+const AUDIO_FIFO_FRAME_SIZE: i32 = 1024;
+
+/// Parses an FFmpeg pixel format name (e.g. `"rgb24"`, `"NV12"`, `"yuv420p"`)
+/// into a `Pixel`, matching case-insensitively against FFmpeg's own names.
+fn parse_pixel_format(name: &str) -> anyhow::Result<Pixel> {
+    name.to_ascii_lowercase()
+        .parse::<Pixel>()
+        .map_err(|e| anyhow::anyhow!("Unknown output pixel format {:?}: {:?}", name, e))
+}
 
 fn is_stream_key_framed(id: Id) -> Result<bool, String> {
     let key_frames = match id {
@@ -55,6 +74,139 @@ fn is_stream_key_framed(id: Id) -> Result<bool, String> {
     }
 }
 
+/// Copies the codec extradata (e.g. the `avcC`/`hvcC` box, or Annex-B SPS/PPS)
+/// FFmpeg attached to this stream's codec parameters.
+fn extract_extradata(parameters: &Parameters) -> Vec<u8> {
+    unsafe {
+        let ptr = parameters.as_ptr();
+        let size = (*ptr).extradata_size as usize;
+        if size == 0 || (*ptr).extradata.is_null() {
+            Vec::new()
+        } else {
+            std::slice::from_raw_parts((*ptr).extradata, size).to_vec()
+        }
+    }
+}
+
+/// Splits Annex-B bitstream data (NAL units separated by `00 00 01` / `00 00
+/// 00 01` start codes) into its NAL units, each returned without the start
+/// code prefix.
+fn split_annex_b_nals(data: &[u8]) -> Vec<&[u8]> {
+    let mut boundaries = Vec::new();
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            boundaries.push((i, i + 3));
+            i += 3;
+        } else if i + 4 <= data.len()
+            && data[i] == 0
+            && data[i + 1] == 0
+            && data[i + 2] == 0
+            && data[i + 3] == 1
+        {
+            boundaries.push((i, i + 4));
+            i += 4;
+        } else {
+            i += 1;
+        }
+    }
+
+    boundaries
+        .iter()
+        .enumerate()
+        .map(|(idx, &(_, nal_start))| {
+            let nal_end = boundaries
+                .get(idx + 1)
+                .map(|&(start_code, _)| start_code)
+                .unwrap_or(data.len());
+            &data[nal_start..nal_end]
+        })
+        .collect()
+}
+
+/// Rewrites Annex-B `data` (start-code-delimited NAL units) into AVC sample
+/// format: each NAL unit prefixed with its 4-byte big-endian length instead
+/// of a start code, as required by MP4/MKV samples using an `avcC`/`hvcC`
+/// box.
+fn annex_b_to_avc(data: &[u8]) -> Vec<u8> {
+    let nals = split_annex_b_nals(data);
+    if nals.is_empty() {
+        if !data.is_empty() {
+            warn!(
+                "annex_b_to_avc: no Annex-B start code found in a {}-byte packet; passing it through unchanged instead of emitting an empty frame",
+                data.len()
+            );
+        }
+        return data.to_vec();
+    }
+
+    let mut out = Vec::with_capacity(data.len());
+    for nal in nals {
+        out.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+        out.extend_from_slice(nal);
+    }
+    out
+}
+
+/// Assembles an `AVCDecoderConfigurationRecord` (the contents of an MP4
+/// `avcC` box: version 1, profile/level taken from the first SPS, a 4-byte
+/// NAL length size, then the SPS and PPS NAL arrays with 2-byte length
+/// prefixes) from H.264 `extradata`. If `extradata` already looks like an
+/// `avcC` record (starts with `configurationVersion == 1`), it is returned
+/// unchanged; otherwise it is assumed to hold Annex-B SPS/PPS NAL units and
+/// a fresh record is built from them. Returns `None` if no SPS could be
+/// found.
+///
+/// Callers must gate on the actual stream codec id being H.264 before
+/// calling this — `(nal_type & 0x1f) == 7/8` is an H.264 NAL type check and
+/// will misinterpret arbitrary non-H.264 extradata (e.g. HEVC, whose NAL
+/// header happens to overlap some of these bit patterns) as SPS/PPS. HEVC's
+/// structurally different `hvcC` record is not built by this function.
+fn build_avc_decoder_configuration_record(extradata: &[u8]) -> Option<Vec<u8>> {
+    if extradata.len() >= 7 && extradata[0] == 1 {
+        return Some(extradata.to_vec());
+    }
+
+    let nals = split_annex_b_nals(extradata);
+    let sps_list: Vec<&[u8]> = nals
+        .iter()
+        .copied()
+        .filter(|n| !n.is_empty() && (n[0] & 0x1f) == 7)
+        .collect();
+    let pps_list: Vec<&[u8]> = nals
+        .iter()
+        .copied()
+        .filter(|n| !n.is_empty() && (n[0] & 0x1f) == 8)
+        .collect();
+
+    let sps = *sps_list.first()?;
+    if sps.len() < 4 {
+        return None;
+    }
+
+    let mut record = vec![
+        1,                                    // configurationVersion
+        sps[1],                               // AVCProfileIndication
+        sps[2],                               // profile_compatibility
+        sps[3],                               // AVCLevelIndication
+        0xfc | 3, // reserved(6) + lengthSizeMinusOne(2): 4-byte NAL lengths
+        0xe0 | (sps_list.len() as u8 & 0x1f), // reserved(3) + numOfSequenceParameterSets(5)
+    ];
+
+    for s in &sps_list {
+        record.extend_from_slice(&(s.len() as u16).to_be_bytes());
+        record.extend_from_slice(s);
+    }
+
+    record.push(pps_list.len() as u8);
+    for p in &pps_list {
+        record.extend_from_slice(&(p.len() as u16).to_be_bytes());
+        record.extend_from_slice(p);
+    }
+
+    Some(record)
+}
+
 #[pyclass]
 #[derive(Clone, Debug)]
 pub struct BsfFilter {
@@ -109,10 +261,76 @@ pub struct VideoFrameEnvelope {
     pub queue_len: i64,
     #[pyo3(get)]
     pub queue_full_skipped_count: i64,
+    /// `true` when `detect_scene_changes` is enabled and this frame was
+    /// flagged as a shot boundary relative to the previously decoded frame.
+    #[pyo3(get)]
+    pub scene_change: bool,
+    /// Byte offset of each plane within `payload`, in plane order.
+    #[pyo3(get)]
+    pub plane_offsets: Vec<i64>,
+    /// Stride (bytes per row) of each plane, in plane order.
+    #[pyo3(get)]
+    pub plane_strides: Vec<i64>,
+    /// Height in rows of each plane, in plane order (differs from
+    /// `frame_height` for sub-sampled planes of planar formats like YUV420P).
+    #[pyo3(get)]
+    pub plane_heights: Vec<i64>,
+    /// Concatenation of every plane's data, in plane order.
     #[pyo3(get)]
     pub payload: Vec<u8>,
 }
 
+#[derive(Debug, Clone)]
+#[pyclass]
+pub struct AudioFrameEnvelope {
+    #[pyo3(get)]
+    pub codec: String,
+    #[pyo3(get)]
+    pub sample_rate: i64,
+    #[pyo3(get)]
+    pub channels: i64,
+    #[pyo3(get)]
+    pub sample_format: String,
+    #[pyo3(get)]
+    pub channel_layout: String,
+    #[pyo3(get)]
+    pub time_base: (i64, i64),
+    #[pyo3(get)]
+    pub pts: Option<i64>,
+    #[pyo3(get)]
+    pub dts: Option<i64>,
+    #[pyo3(get)]
+    pub corrupted: bool,
+    #[pyo3(get)]
+    pub queue_len: i64,
+    #[pyo3(get)]
+    pub queue_full_skipped_count: i64,
+    #[pyo3(get)]
+    pub payload: Vec<u8>,
+}
+
+#[pymethods]
+impl AudioFrameEnvelope {
+    #[classattr]
+    const __hash__: Option<Py<PyAny>> = None;
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    fn __str__(&self) -> String {
+        self.__repr__()
+    }
+
+    fn payload_as_bytes(&self, py: Python) -> PyResult<PyObject> {
+        let bytes = PyBytes::new_bound_with(py, self.payload.len(), |b: &mut [u8]| {
+            b.copy_from_slice(&self.payload);
+            Ok(())
+        })?;
+        Ok(PyObject::from(bytes))
+    }
+}
+
 #[pyclass(eq, eq_int)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum FFmpegLogLevel {
@@ -151,9 +369,12 @@ impl VideoFrameEnvelope {
 #[pyclass]
 pub struct FFMpegSource {
     video_source: Receiver<VideoFrameEnvelope>,
+    audio_source: Receiver<AudioFrameEnvelope>,
     thread: Option<JoinHandle<anyhow::Result<()>>>,
     exit_signal: Arc<Mutex<bool>>,
     log_level: Arc<Mutex<Option<Level>>>,
+    extradata: Vec<u8>,
+    video_codec_id: Id,
 }
 
 impl Drop for FFMpegSource {
@@ -190,15 +411,399 @@ fn handle_wrapper(params: HandleParams) -> anyhow::Result<()> {
 #[derive(Builder)]
 struct HandleParams {
     uri: String,
+    source: InputSource,
     params: Vec<(String, String)>,
     tx: Sender<VideoFrameEnvelope>,
-    init_complete: Sender<()>,
+    audio_tx: Sender<AudioFrameEnvelope>,
+    init_complete: Sender<(Vec<u8>, Id)>,
     exit_signal: Arc<Mutex<bool>>,
     decode: bool,
+    with_audio: bool,
     autoconvert_raw_formats_to_rgb24: bool,
     block_if_queue_full: bool,
     log_level: Arc<Mutex<Option<Level>>>,
     bsf_filters: Vec<BsfFilter>,
+    record_to: Option<String>,
+    segment_seconds: u64,
+    segment_format: String,
+    filter_description: Option<String>,
+    output_pixel_format: Pixel,
+    output_width: Option<u32>,
+    output_height: Option<u32>,
+    detect_scene_changes: bool,
+    scene_change_threshold: f64,
+    scene_change_min_frames: u64,
+    annex_b_to_avc: bool,
+}
+
+struct AudioFifo {
+    ptr: *mut AVAudioFifo,
+}
+
+impl AudioFifo {
+    fn new(sample_fmt: ffmpeg::format::Sample, channels: i32) -> anyhow::Result<Self> {
+        let fmt: ffmpeg_next::sys::AVSampleFormat = sample_fmt.into();
+        let ptr = unsafe { av_audio_fifo_alloc(fmt, channels, AUDIO_FIFO_FRAME_SIZE) };
+        if ptr.is_null() {
+            bail!("Unable to allocate audio FIFO");
+        }
+        Ok(Self { ptr })
+    }
+
+    fn write(&mut self, frame: &Audio) -> anyhow::Result<()> {
+        unsafe {
+            let data = (*frame.as_ptr()).extended_data as *mut *mut c_void;
+            let ret = av_audio_fifo_write(self.ptr, data, frame.samples() as i32);
+            if ret < 0 {
+                bail!("Unable to write samples to audio FIFO");
+            }
+        }
+        Ok(())
+    }
+
+    fn available(&self) -> i32 {
+        unsafe { av_audio_fifo_size(self.ptr) }
+    }
+
+    fn read(&mut self, frame: &mut Audio, samples: i32) -> anyhow::Result<()> {
+        unsafe {
+            let data = (*frame.as_mut_ptr()).extended_data as *mut *mut c_void;
+            let ret = av_audio_fifo_read(self.ptr, data, samples);
+            if ret < 0 {
+                bail!("Unable to read samples from audio FIFO");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AudioFifo {
+    fn drop(&mut self) {
+        unsafe {
+            av_audio_fifo_free(self.ptr);
+        }
+    }
+}
+
+enum InputSource {
+    Uri,
+    Callback {
+        read_callback: Py<PyAny>,
+        seek_callback: Option<Py<PyAny>>,
+        buffer_size: usize,
+    },
+}
+
+struct AvioState {
+    read_callback: Py<PyAny>,
+    seek_callback: Option<Py<PyAny>>,
+}
+
+unsafe extern "C" fn avio_read_packet(opaque: *mut c_void, buf: *mut u8, buf_size: c_int) -> c_int {
+    let state = &*(opaque as *const AvioState);
+    Python::with_gil(|py| {
+        let res = state.read_callback.bind(py).call1((buf_size,));
+        match res.and_then(|r| r.extract::<Vec<u8>>()) {
+            Ok(bytes) if bytes.is_empty() => AVERROR_EOF,
+            Ok(bytes) => {
+                let n = bytes.len().min(buf_size as usize);
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), buf, n);
+                n as c_int
+            }
+            Err(e) => {
+                error!("Python read callback failed: {:?}", e);
+                AVERROR(EAGAIN)
+            }
+        }
+    })
+}
+
+unsafe extern "C" fn avio_seek_packet(opaque: *mut c_void, offset: i64, whence: c_int) -> i64 {
+    let state = &*(opaque as *const AvioState);
+    let Some(seek_callback) = state.seek_callback.as_ref() else {
+        return -1;
+    };
+    Python::with_gil(|py| {
+        match seek_callback
+            .bind(py)
+            .call1((offset, whence))
+            .and_then(|r| r.extract::<i64>())
+        {
+            Ok(pos) => pos,
+            Err(e) => {
+                error!("Python seek callback failed: {:?}", e);
+                -1
+            }
+        }
+    })
+}
+
+struct CustomAvioContext {
+    ctx: *mut AVIOContext,
+    state: *mut AvioState,
+}
+
+impl CustomAvioContext {
+    fn new(
+        read_callback: Py<PyAny>,
+        seek_callback: Option<Py<PyAny>>,
+        buffer_size: usize,
+    ) -> anyhow::Result<Self> {
+        unsafe {
+            let buffer = av_malloc(buffer_size) as *mut u8;
+            if buffer.is_null() {
+                bail!("Unable to allocate AVIO buffer");
+            }
+
+            let has_seek = seek_callback.is_some();
+            let state = Box::into_raw(Box::new(AvioState {
+                read_callback,
+                seek_callback,
+            }));
+
+            let ctx = avio_alloc_context(
+                buffer,
+                buffer_size as c_int,
+                0,
+                state as *mut c_void,
+                Some(avio_read_packet),
+                None,
+                if has_seek {
+                    Some(avio_seek_packet)
+                } else {
+                    None
+                },
+            );
+
+            if ctx.is_null() {
+                av_free(buffer as *mut c_void);
+                drop(Box::from_raw(state));
+                bail!("Unable to allocate AVIOContext");
+            }
+
+            Ok(Self { ctx, state })
+        }
+    }
+}
+
+impl Drop for CustomAvioContext {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.ctx.is_null() {
+                av_free((*self.ctx).buffer as *mut c_void);
+                let mut ctx = self.ctx;
+                avio_context_free(&mut ctx);
+            }
+            drop(Box::from_raw(self.state));
+        }
+    }
+}
+
+fn open_input_from_avio(
+    avio_ctx: *mut AVIOContext,
+    mut opts: ffmpeg::Dictionary,
+) -> anyhow::Result<Input> {
+    unsafe {
+        let fmt_ctx = avformat_alloc_context();
+        if fmt_ctx.is_null() {
+            bail!("Unable to allocate AVFormatContext");
+        }
+        (*fmt_ctx).pb = avio_ctx;
+        (*fmt_ctx).flags |= ffmpeg_next::sys::AVFMT_FLAG_CUSTOM_IO as i32;
+
+        let mut raw_ctx = fmt_ctx;
+        let mut dict_ptr = opts.disown();
+        let ret = avformat_open_input(
+            &mut raw_ctx,
+            std::ptr::null(),
+            std::ptr::null_mut(),
+            &mut dict_ptr,
+        );
+        opts = ffmpeg::Dictionary::own(dict_ptr);
+        drop(opts);
+
+        if ret < 0 {
+            bail!(
+                "Unable to open input from the custom AVIO source, error code: {}",
+                ret
+            );
+        }
+
+        let ret = avformat_find_stream_info(raw_ctx, std::ptr::null_mut());
+        if ret < 0 {
+            avformat_free_context(raw_ctx);
+            bail!(
+                "Unable to find stream info for the custom AVIO source, error code: {}",
+                ret
+            );
+        }
+
+        Ok(Input::wrap(raw_ctx))
+    }
+}
+
+struct CurrentSegment {
+    ctx: *mut AVFormatContext,
+    stream_index: i32,
+    segment_start_pts: i64,
+}
+
+/// Muxes passthrough video packets into keyframe-aligned segment files on
+/// disk, in parallel with delivering `VideoFrameEnvelope`s to Python. A new
+/// segment is started whenever a keyframe arrives and at least
+/// `segment_seconds` have elapsed since the current segment began.
+struct SegmentRecorder {
+    dir: PathBuf,
+    container: &'static str,
+    extension: &'static str,
+    segment_seconds: i64,
+    time_base: Rational,
+    segment_index: u64,
+    current: Option<CurrentSegment>,
+}
+
+impl SegmentRecorder {
+    fn new(dir: String, segment_format: &str, segment_seconds: u64, time_base: Rational) -> Self {
+        let (container, extension) = match segment_format {
+            "ts" => ("mpegts", "ts"),
+            _ => ("mp4", "mp4"),
+        };
+        Self {
+            dir: PathBuf::from(dir),
+            container,
+            extension,
+            segment_seconds: segment_seconds as i64,
+            time_base,
+            segment_index: 0,
+            current: None,
+        }
+    }
+
+    fn start_segment(&mut self, video_parameters: &Parameters) -> anyhow::Result<()> {
+        unsafe {
+            let path = self.dir.join(format!(
+                "segment_{:06}.{}",
+                self.segment_index, self.extension
+            ));
+            let c_path = CString::new(path.to_string_lossy().into_owned())?;
+            let c_format = CString::new(self.container)?;
+
+            let mut ctx: *mut AVFormatContext = std::ptr::null_mut();
+            let ret = avformat_alloc_output_context2(
+                &mut ctx,
+                std::ptr::null(),
+                c_format.as_ptr(),
+                c_path.as_ptr(),
+            );
+            if ret < 0 || ctx.is_null() {
+                bail!("Unable to allocate output context for segment recording");
+            }
+
+            let out_stream = avformat_new_stream(ctx, std::ptr::null());
+            if out_stream.is_null() {
+                avformat_free_context(ctx);
+                bail!("Unable to allocate output stream for segment recording");
+            }
+            if avcodec_parameters_copy((*out_stream).codecpar, video_parameters.as_ptr()) < 0 {
+                avformat_free_context(ctx);
+                bail!("Unable to copy codec parameters to the output stream");
+            }
+            // A codec_tag valid in the source container (e.g. an AVI/MOV
+            // fourcc) can be rejected by the segment muxer below; FFmpeg's
+            // own remuxing.c example zeroes it for the same reason, letting
+            // the muxer pick a tag appropriate for the output container.
+            (*(*out_stream).codecpar).codec_tag = 0;
+            (*out_stream).time_base = self.time_base.into();
+            let stream_index = (*out_stream).index;
+
+            if avio_open(&mut (*ctx).pb, c_path.as_ptr(), AVIO_FLAG_WRITE as i32) < 0 {
+                avformat_free_context(ctx);
+                bail!("Unable to open segment output file: {:?}", path);
+            }
+
+            if avformat_write_header(ctx, std::ptr::null_mut()) < 0 {
+                bail!("Unable to write segment header for: {:?}", path);
+            }
+
+            info!("Started new recording segment: {:?}", path);
+            self.current = Some(CurrentSegment {
+                ctx,
+                stream_index,
+                segment_start_pts: 0,
+            });
+            self.segment_index += 1;
+        }
+        Ok(())
+    }
+
+    fn finish_segment(&mut self) {
+        if let Some(current) = self.current.take() {
+            unsafe {
+                av_write_trailer(current.ctx);
+                avio_closep(&mut (*current.ctx).pb);
+                avformat_free_context(current.ctx);
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, packet: &Packet, is_key: bool, video_parameters: &Parameters) {
+        let pts = packet.pts().or_else(|| packet.dts()).unwrap_or(0);
+
+        if self.current.is_none() {
+            if !is_key {
+                return;
+            }
+            if let Err(e) = self.start_segment(video_parameters) {
+                error!("Unable to start recording segment. Error is: {:?}", e);
+                return;
+            }
+        } else if is_key {
+            let elapsed = self.current.as_ref().map(|c| pts - c.segment_start_pts);
+            let elapsed_seconds = elapsed.unwrap_or(0) as f64
+                * f64::from(self.time_base.numerator())
+                / f64::from(self.time_base.denominator());
+            if elapsed_seconds >= self.segment_seconds as f64 {
+                self.finish_segment();
+                if let Err(e) = self.start_segment(video_parameters) {
+                    error!("Unable to start recording segment. Error is: {:?}", e);
+                    return;
+                }
+            }
+        }
+
+        let current = match self.current.as_mut() {
+            Some(c) => c,
+            None => return,
+        };
+        if current.segment_start_pts == 0 {
+            current.segment_start_pts = pts;
+        }
+
+        let mut out_packet = packet.clone();
+        unsafe {
+            let in_tb: ffmpeg_next::ffi::AVRational = self.time_base.into();
+            let out_tb = (**(*current.ctx).streams).time_base;
+            let raw = out_packet.as_mut_ptr();
+            if (*raw).pts != AV_NOPTS_VALUE {
+                (*raw).pts = av_rescale_q((*raw).pts, in_tb, out_tb);
+            }
+            if (*raw).dts != AV_NOPTS_VALUE {
+                (*raw).dts = av_rescale_q((*raw).dts, in_tb, out_tb);
+            }
+            (*raw).duration = av_rescale_q((*raw).duration, in_tb, out_tb);
+            (*raw).stream_index = current.stream_index;
+
+            if av_interleaved_write_frame(current.ctx, raw) < 0 {
+                error!("Unable to write packet to the current recording segment");
+            }
+        }
+    }
+}
+
+impl Drop for SegmentRecorder {
+    fn drop(&mut self) {
+        self.finish_segment();
+    }
 }
 
 struct BitStreamFilterContext {
@@ -300,9 +905,222 @@ fn process_bsf(
     Ok(packets)
 }
 
+/// Delivers a decoded audio frame to `audio_tx`, always skipping this new
+/// frame rather than blocking when the queue is full. Video and
+/// audio packets are demuxed on the same worker thread, so unlike the video
+/// `tx` channel, `audio_tx` never honors `block_if_queue_full`: a caller
+/// that enables audio but never drains `audio_frame()` must not be able to
+/// stall video delivery by filling this queue.
+fn send_audio_frame(
+    params: &HandleParams,
+    frame_envelope: AudioFrameEnvelope,
+    queue_full_skipped_count: &mut i64,
+) {
+    if !params.audio_tx.is_full() {
+        if let Err(e) = params.audio_tx.send(frame_envelope) {
+            error!("Unable to send audio data to upstream. Error is: {:?}", e);
+        }
+    } else {
+        warn!("Audio sink queue is full, the frame is skipped.");
+        *queue_full_skipped_count += 1;
+    }
+}
+
+/// Builds a `buffer` -> ... -> `buffersink` filtergraph from `filter_spec`
+/// (e.g. `"scale=640:-1,fps=15,hqdn3d"`), fed with frames in the decoder's
+/// native size/format/time base. The sink is not constrained to any pixel
+/// format, so frames leave the graph in whatever format the filter chain
+/// produces (the decoder's own, for filters that only reshape or denoise the
+/// frame: scale, fps, hqdn3d, ...) without an extra conversion; the final conversion
+/// to the delivery pixel format still happens afterwards via `swscale`.
+fn build_video_filter_graph(
+    decoder: &ffmpeg::decoder::Video,
+    filter_spec: &str,
+) -> anyhow::Result<ffmpeg::filter::Graph> {
+    let mut graph = ffmpeg::filter::Graph::new();
+
+    let args = format!(
+        "video_size={}x{}:pix_fmt={}:time_base={}/{}:pixel_aspect={}/{}",
+        decoder.width(),
+        decoder.height(),
+        decoder
+            .format()
+            .descriptor()
+            .map(|d| d.name())
+            .unwrap_or("none"),
+        decoder.time_base().numerator(),
+        decoder.time_base().denominator(),
+        decoder.aspect_ratio().numerator().max(1),
+        decoder.aspect_ratio().denominator().max(1),
+    );
+
+    let buffer = ffmpeg::filter::find("buffer")
+        .ok_or_else(|| anyhow::anyhow!("Unable to find the buffer filter"))?;
+    let buffersink = ffmpeg::filter::find("buffersink")
+        .ok_or_else(|| anyhow::anyhow!("Unable to find the buffersink filter"))?;
+
+    graph.add(&buffer, "in", &args)?;
+    graph.add(&buffersink, "out", "")?;
+
+    graph.output("in", 0)?.input("out", 0)?.parse(filter_spec)?;
+    graph.validate()?;
+
+    Ok(graph)
+}
+
+/// A decoded video frame packed for delivery: `payload` is the concatenation
+/// of every plane's bytes in plane order, with `plane_offsets`/`plane_strides`/
+/// `plane_heights` giving each plane's position within it. Packed formats
+/// (e.g. RGB24) have a single plane; planar formats (e.g. YUV420P) have one
+/// per component, possibly sub-sampled (so `plane_heights[i]` can be smaller
+/// than `height`).
+struct DecodedFrame {
+    payload: Vec<u8>,
+    plane_offsets: Vec<i64>,
+    plane_strides: Vec<i64>,
+    plane_heights: Vec<i64>,
+    width: u32,
+    height: u32,
+    scene_change: bool,
+}
+
+fn pack_planes(frame: &Video, scene_change: bool) -> DecodedFrame {
+    let mut payload = Vec::new();
+    let mut plane_offsets = Vec::new();
+    let mut plane_strides = Vec::new();
+    let mut plane_heights = Vec::new();
+
+    for i in 0..frame.planes() {
+        plane_offsets.push(payload.len() as i64);
+        payload.extend_from_slice(frame.data(i));
+        plane_strides.push(frame.stride(i) as i64);
+        plane_heights.push(i64::from(frame.plane_height(i)));
+    }
+
+    DecodedFrame {
+        payload,
+        plane_offsets,
+        plane_strides,
+        plane_heights,
+        width: frame.width(),
+        height: frame.height(),
+        scene_change,
+    }
+}
+
+/// Side of `SCENE_CHANGE_GRID` x `SCENE_CHANGE_GRID` grid that each decoded
+/// frame's first plane is downscaled to before comparison.
+const SCENE_CHANGE_GRID: usize = 32;
+/// Number of bins in the coarse luma histogram used for the chi-square test.
+const SCENE_CHANGE_HIST_BINS: usize = 16;
+
+/// Flags keyframe-independent shot boundaries by comparing each decoded
+/// frame's first plane (the luma plane for YUV formats; one byte per pixel
+/// is sampled, whatever the decoder's format), downscaled to a small fixed grid, against the
+/// previous frame: a cut is reported when either the normalized sum of
+/// absolute grid differences or the luma-histogram chi-square distance
+/// exceeds `threshold`, and at least `min_frames_between_cuts` frames have
+/// passed since the last reported cut (to suppress flicker). Only the
+/// previous frame's reduced grid and histogram are kept as state.
+struct SceneChangeDetector {
+    threshold: f64,
+    min_frames_between_cuts: u64,
+    frames_since_cut: u64,
+    previous: Option<(
+        [u8; SCENE_CHANGE_GRID * SCENE_CHANGE_GRID],
+        [u32; SCENE_CHANGE_HIST_BINS],
+    )>,
+}
+
+impl SceneChangeDetector {
+    fn new(threshold: f64, min_frames_between_cuts: u64) -> Self {
+        Self {
+            threshold,
+            min_frames_between_cuts,
+            frames_since_cut: min_frames_between_cuts,
+            previous: None,
+        }
+    }
+
+    fn reduce(
+        frame: &Video,
+    ) -> (
+        [u8; SCENE_CHANGE_GRID * SCENE_CHANGE_GRID],
+        [u32; SCENE_CHANGE_HIST_BINS],
+    ) {
+        let plane = frame.data(0);
+        let stride = frame.stride(0);
+        let width = frame.plane_width(0).max(1) as usize;
+        let height = frame.plane_height(0).max(1) as usize;
+
+        let mut grid = [0u8; SCENE_CHANGE_GRID * SCENE_CHANGE_GRID];
+        let mut histogram = [0u32; SCENE_CHANGE_HIST_BINS];
+
+        for gy in 0..SCENE_CHANGE_GRID {
+            let sy = (gy * height / SCENE_CHANGE_GRID).min(height - 1);
+            for gx in 0..SCENE_CHANGE_GRID {
+                let sx = (gx * width / SCENE_CHANGE_GRID).min(width - 1);
+                let value = plane[sy * stride + sx];
+                grid[gy * SCENE_CHANGE_GRID + gx] = value;
+                histogram[usize::from(value) * SCENE_CHANGE_HIST_BINS / 256] += 1;
+            }
+        }
+
+        (grid, histogram)
+    }
+
+    /// Feeds the next decoded frame to the detector and reports whether it is
+    /// a scene cut relative to the previous one.
+    fn detect(&mut self, frame: &Video) -> bool {
+        let (grid, histogram) = Self::reduce(frame);
+        self.frames_since_cut += 1;
+
+        let grid_len = (SCENE_CHANGE_GRID * SCENE_CHANGE_GRID) as f64;
+        let exceeds_threshold = match &self.previous {
+            Some((prev_grid, prev_histogram)) => {
+                let sad = grid
+                    .iter()
+                    .zip(prev_grid.iter())
+                    .map(|(a, b)| f64::from((*a).abs_diff(*b)))
+                    .sum::<f64>()
+                    / grid_len
+                    / 255.0;
+
+                let chi_square = histogram
+                    .iter()
+                    .zip(prev_histogram.iter())
+                    .map(|(a, b)| {
+                        let diff = f64::from(*a) - f64::from(*b);
+                        let denom = f64::from(*a) + f64::from(*b);
+                        if denom > 0.0 {
+                            diff * diff / denom
+                        } else {
+                            0.0
+                        }
+                    })
+                    .sum::<f64>()
+                    / grid_len;
+
+                sad > self.threshold || chi_square > self.threshold
+            }
+            None => false,
+        };
+
+        self.previous = Some((grid, histogram));
+
+        if exceeds_threshold && self.frames_since_cut >= self.min_frames_between_cuts {
+            self.frames_since_cut = 0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn handle(params: HandleParams) -> anyhow::Result<()> {
     let mut queue_full_skipped_count = 0;
+    let mut audio_queue_full_skipped_count = 0;
     let now = Instant::now();
     ffmpeg::init().map_err(|e| {
         error!("Unable to initialize FFmpeg. Error is: {:?}", e);
@@ -320,12 +1138,34 @@ fn handle(params: HandleParams) -> anyhow::Result<()> {
     for (k, v) in &params.params {
         opts.set(k, v);
     }
-    let p = Path::new(params.uri.as_str());
 
-    let mut ictx = input_with_dictionary(&p, opts).map_err(|e| {
-        error!("Unable to open input URI. Error is: {:?}", e);
-        e
-    })?;
+    // Kept alive for the whole worker loop: the AVFormatContext we open below
+    // borrows its buffer and read/seek callbacks for as long as it is in use.
+    let mut _custom_avio = None;
+
+    let mut ictx = match &params.source {
+        InputSource::Uri => {
+            let p = Path::new(params.uri.as_str());
+            input_with_dictionary(&p, opts).map_err(|e| {
+                error!("Unable to open input URI. Error is: {:?}", e);
+                anyhow::Error::from(e)
+            })?
+        }
+        InputSource::Callback {
+            read_callback,
+            seek_callback,
+            buffer_size,
+        } => {
+            let avio =
+                CustomAvioContext::new(read_callback.clone(), seek_callback.clone(), *buffer_size)?;
+            let ictx = open_input_from_avio(avio.ctx, opts).map_err(|e| {
+                error!("Unable to open input from callback. Error is: {:?}", e);
+                e
+            })?;
+            _custom_avio = Some(avio);
+            ictx
+        }
+    };
 
     let video_input = match ictx.streams().best(ffmpeg_next::media::Type::Video) {
         Some(s) => s,
@@ -337,6 +1177,23 @@ fn handle(params: HandleParams) -> anyhow::Result<()> {
     };
     let video_parameters = video_input.parameters();
     let time_base = video_input.time_base();
+    // Same check as `build_avc_decoder_configuration_record`: a
+    // `configurationVersion == 1` extradata means the source already hands
+    // us length-prefixed AVC samples, so Annex-B conversion would be a no-op
+    // at best and must not be attempted.
+    let source_is_already_avc = {
+        let extradata = extract_extradata(&video_parameters);
+        extradata.len() >= 7 && extradata[0] == 1
+    };
+
+    let mut recorder = params.record_to.as_ref().map(|dir| {
+        SegmentRecorder::new(
+            dir.clone(),
+            params.segment_format.as_str(),
+            params.segment_seconds,
+            time_base,
+        )
+    });
 
     info!("Codec: {:?}", video_input.codec().id());
 
@@ -375,32 +1232,55 @@ fn handle(params: HandleParams) -> anyhow::Result<()> {
                 e
             })?;
 
-    let mut converter = converter(
-        (video_decoder.width(), video_decoder.height()),
-        video_decoder.format(),
-        DECODING_FORMAT,
-    )
-    .map_err(|e| {
-        error!("Unable to get video converter. Error is: {:?}", e);
-        e
-    })?;
+    let mut converter = None;
+
+    let mut video_filter_graph = match params.filter_description.as_deref() {
+        Some(spec) => Some(build_video_filter_graph(&video_decoder, spec)?),
+        None => None,
+    };
 
-    let audio_stream_index_opt = ictx
-        .streams()
-        .best(ffmpeg_next::media::Type::Audio)
-        .map(|s| s.index());
+    let mut scene_change_detector = params.detect_scene_changes.then(|| {
+        SceneChangeDetector::new(
+            params.scene_change_threshold,
+            params.scene_change_min_frames,
+        )
+    });
+
+    let audio_stream_index_opt = if params.with_audio {
+        ictx.streams()
+            .best(ffmpeg_next::media::Type::Audio)
+            .map(|s| s.index())
+    } else {
+        None
+    };
+
+    let mut audio_decoder_opt = audio_stream_index_opt.and_then(|_| {
+        ictx.streams()
+            .best(ffmpeg_next::media::Type::Audio)
+            .and_then(|s| ffmpeg::codec::context::Context::from_parameters(s.parameters()).ok())
+            .and_then(|c| c.decoder().audio().ok())
+    });
 
-    let audio_opt = ictx
-        .streams()
-        .best(ffmpeg_next::media::Type::Audio)
-        .and_then(|s| ffmpeg::codec::context::Context::from_parameters(s.parameters()).ok())
-        .and_then(|c| c.decoder().audio().ok());
+    let mut audio_fifo: Option<AudioFifo> = None;
+    // The FIFO repacks decoded frames into fixed-size `AUDIO_FIFO_FRAME_SIZE`
+    // chunks, so a single output frame no longer lines up with a single
+    // input packet's pts. Instead we anchor on the pts of the first decoded
+    // frame and derive every later frame's pts from how many samples have
+    // been emitted since, the way an FFmpeg-based resampler would.
+    let mut audio_base_pts: Option<i64> = None;
+    let mut audio_samples_emitted: i64 = 0;
 
     let mut skip_until_first_key_frame = true;
-    params.init_complete.send(()).map_err(|e| {
-        error!("Unable to send init complete signal. Error is: {:?}", e);
-        e
-    })?;
+    params
+        .init_complete
+        .send((
+            extract_extradata(&video_parameters),
+            video_input.codec().id(),
+        ))
+        .map_err(|e| {
+            error!("Unable to send init complete signal. Error is: {:?}", e);
+            e
+        })?;
     info!(
         "FFmpeg is initialized for URI: {}, elapsed: {:?}",
         params.uri,
@@ -431,11 +1311,111 @@ fn handle(params: HandleParams) -> anyhow::Result<()> {
 
         if let Some(index) = audio_stream_index_opt {
             if index == stream.index() {
-                if let Some(name) = audio_opt
-                    .as_ref()
-                    .and_then(|a| a.codec().as_ref().map(|c| String::from(c.name())))
-                {
-                    debug!("Audio streams are not supported yet. Codec is {}", name);
+                let audio_time_base = stream.time_base();
+                let codec = match audio_decoder_opt.as_ref().and_then(|a| a.codec()) {
+                    Some(c) => String::from(c.name()),
+                    None => bail!("Unable to get audio codec name"),
+                };
+
+                if params.decode {
+                    if let Some(audio_decoder) = audio_decoder_opt.as_mut() {
+                        audio_decoder.send_packet(&packet).map_err(|e| {
+                            error!("Unable to send packet to audio decoder. Error is: {:?}", e);
+                            e
+                        })?;
+                        let mut decoded = Audio::empty();
+                        while audio_decoder.receive_frame(&mut decoded).is_ok() {
+                            if audio_fifo.is_none() {
+                                audio_fifo = Some(AudioFifo::new(
+                                    decoded.format(),
+                                    i32::from(decoded.channels()),
+                                )?);
+                            }
+                            if audio_base_pts.is_none() {
+                                audio_base_pts = decoded.pts().or_else(|| packet.pts());
+                            }
+                            let fifo = audio_fifo.as_mut().unwrap();
+                            fifo.write(&decoded)?;
+
+                            while fifo.available() >= AUDIO_FIFO_FRAME_SIZE {
+                                let mut fixed_frame = Audio::new(
+                                    decoded.format(),
+                                    AUDIO_FIFO_FRAME_SIZE as usize,
+                                    decoded.channel_layout(),
+                                );
+                                fixed_frame.set_rate(decoded.rate());
+                                fifo.read(&mut fixed_frame, AUDIO_FIFO_FRAME_SIZE)?;
+
+                                let frame_pts = audio_base_pts.map(|base| {
+                                    let samples_tb = ffmpeg_next::ffi::AVRational {
+                                        num: 1,
+                                        den: fixed_frame.rate() as c_int,
+                                    };
+                                    let out_tb: ffmpeg_next::ffi::AVRational =
+                                        audio_time_base.into();
+                                    base + unsafe {
+                                        av_rescale_q(audio_samples_emitted, samples_tb, out_tb)
+                                    }
+                                });
+                                audio_samples_emitted += i64::from(AUDIO_FIFO_FRAME_SIZE);
+
+                                let mut payload = Vec::new();
+                                for i in 0..fixed_frame.planes() {
+                                    payload.extend_from_slice(fixed_frame.data(i));
+                                }
+
+                                let frame_envelope = AudioFrameEnvelope {
+                                    codec: codec.clone(),
+                                    sample_rate: i64::from(fixed_frame.rate()),
+                                    channels: i64::from(fixed_frame.channels()),
+                                    sample_format: format!("{:?}", fixed_frame.format()),
+                                    channel_layout: format!("{:?}", fixed_frame.channel_layout()),
+                                    time_base: (audio_time_base.0 as i64, audio_time_base.1 as i64),
+                                    pts: frame_pts,
+                                    dts: frame_pts,
+                                    corrupted: packet.is_corrupt(),
+                                    queue_full_skipped_count: audio_queue_full_skipped_count,
+                                    queue_len: i64::try_from(params.audio_tx.len()).unwrap(),
+                                    payload,
+                                };
+
+                                send_audio_frame(
+                                    &params,
+                                    frame_envelope,
+                                    &mut audio_queue_full_skipped_count,
+                                );
+                            }
+                        }
+                    }
+                } else {
+                    let frame_envelope = AudioFrameEnvelope {
+                        codec,
+                        sample_rate: audio_decoder_opt
+                            .as_ref()
+                            .map(|a| i64::from(a.rate()))
+                            .unwrap_or_default(),
+                        channels: audio_decoder_opt
+                            .as_ref()
+                            .map(|a| i64::from(a.channels()))
+                            .unwrap_or_default(),
+                        sample_format: audio_decoder_opt
+                            .as_ref()
+                            .map(|a| format!("{:?}", a.format()))
+                            .unwrap_or_default(),
+                        channel_layout: audio_decoder_opt
+                            .as_ref()
+                            .map(|a| format!("{:?}", a.channel_layout()))
+                            .unwrap_or_default(),
+                        time_base: (audio_time_base.0 as i64, audio_time_base.1 as i64),
+                        pts: packet.pts(),
+                        dts: packet.dts(),
+                        corrupted: packet.is_corrupt(),
+                        queue_full_skipped_count: audio_queue_full_skipped_count,
+                        queue_len: i64::try_from(params.audio_tx.len()).unwrap(),
+                        payload: packet.data().unwrap_or(&[]).to_vec(),
+                    };
+
+                    send_audio_frame(&params, frame_envelope, &mut audio_queue_full_skipped_count);
                 }
             }
         }
@@ -468,6 +1448,10 @@ fn handle(params: HandleParams) -> anyhow::Result<()> {
                     continue;
                 }
 
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.handle_packet(p, p.is_key(), &video_parameters);
+                }
+
                 let decode = params.decode
                     || (params.autoconvert_raw_formats_to_rgb24
                         && video_decoder.codec().map(|c| c.id()) == Some(Id::RAWVIDEO));
@@ -480,27 +1464,101 @@ fn handle(params: HandleParams) -> anyhow::Result<()> {
                     })?;
                     let mut decoded = Video::empty();
                     while video_decoder.receive_frame(&mut decoded).is_ok() {
-                        let mut rgb_frame = Video::empty();
-                        converter.run(&decoded, &mut rgb_frame).map_err(|e| {
-                            error!("Unable to convert frame to RGB. Error is: {:?}", e);
-                            e
-                        })?;
-                        raw_frames.push((
-                            rgb_frame.data(0).to_vec(),
-                            rgb_frame.stride(0) as u32 / DECODED_PIX_BYTES,
-                            rgb_frame.plane_height(0),
-                        ));
+                        let scene_change = scene_change_detector
+                            .as_mut()
+                            .map(|detector| detector.detect(&decoded))
+                            .unwrap_or(false);
+
+                        let filtered_frames = if let Some(graph) = video_filter_graph.as_mut() {
+                            graph
+                                .get("in")
+                                .ok_or_else(|| anyhow::anyhow!("Filtergraph is missing its input"))?
+                                .source()
+                                .add(&decoded)
+                                .map_err(|e| {
+                                    error!(
+                                        "Unable to push frame into the filtergraph. Error is: {:?}",
+                                        e
+                                    );
+                                    e
+                                })?;
+
+                            let mut filtered_frames = Vec::new();
+                            loop {
+                                let mut filtered = Video::empty();
+                                let mut sink = graph.get("out").ok_or_else(|| {
+                                    anyhow::anyhow!("Filtergraph is missing its output")
+                                })?;
+                                if sink.sink().frame(&mut filtered).is_err() {
+                                    break;
+                                }
+                                filtered_frames.push(filtered);
+                            }
+                            filtered_frames
+                        } else {
+                            vec![decoded.clone()]
+                        };
+
+                        for filtered in filtered_frames {
+                            let target_width = params.output_width.unwrap_or(filtered.width());
+                            let target_height = params.output_height.unwrap_or(filtered.height());
+
+                            let conv = match converter.as_mut() {
+                                Some(conv) => conv,
+                                None => {
+                                    converter = Some(
+                                        ffmpeg_next::software::scaling::context::Context::get(
+                                            filtered.format(),
+                                            filtered.width(),
+                                            filtered.height(),
+                                            params.output_pixel_format,
+                                            target_width,
+                                            target_height,
+                                            ffmpeg_next::software::scaling::flag::Flags::BILINEAR,
+                                        )
+                                        .map_err(|e| {
+                                            error!(
+                                                "Unable to get video converter. Error is: {:?}",
+                                                e
+                                            );
+                                            e
+                                        })?,
+                                    );
+                                    converter.as_mut().unwrap()
+                                }
+                            };
+
+                            let mut converted_frame = Video::empty();
+                            conv.run(&filtered, &mut converted_frame).map_err(|e| {
+                                error!("Unable to convert frame. Error is: {:?}", e);
+                                e
+                            })?;
+                            raw_frames.push(pack_planes(&converted_frame, scene_change));
+                        }
                     }
                     raw_frames
                 } else {
-                    vec![(
-                        p.data().unwrap_or(&[]).to_vec(),
-                        video_decoder.width(),
-                        video_decoder.height(),
-                    )]
+                    let raw_data = p.data().unwrap_or(&[]);
+                    let data = if params.annex_b_to_avc && !source_is_already_avc {
+                        annex_b_to_avc(raw_data)
+                    } else {
+                        raw_data.to_vec()
+                    };
+                    vec![DecodedFrame {
+                        plane_offsets: vec![0],
+                        plane_strides: vec![data.len() as i64],
+                        plane_heights: vec![i64::from(video_decoder.height())],
+                        payload: data,
+                        width: video_decoder.width(),
+                        height: video_decoder.height(),
+                        scene_change: false,
+                    }]
                 };
 
-                for (raw_frame, width, height) in raw_frames {
+                for frame in raw_frames {
+                    let width = frame.width;
+                    let height = frame.height;
+
                     let codec = if !decode {
                         match video_decoder.codec() {
                             Some(c) => String::from(c.name()),
@@ -513,7 +1571,7 @@ fn handle(params: HandleParams) -> anyhow::Result<()> {
                     let pixel_format = if !decode {
                         format!("{:?}", video_decoder.format())
                     } else {
-                        format!("{:?}", DECODING_FORMAT)
+                        format!("{:?}", params.output_pixel_format)
                     };
 
                     let key_frame = p.is_key();
@@ -524,7 +1582,7 @@ fn handle(params: HandleParams) -> anyhow::Result<()> {
                     let avg_fps = stream.avg_frame_rate().to_string();
 
                     debug!("Frame info: codec_name={:?}, FPS={:?}, AVG_FPS={:?}, width={}, height={}, is_key={}, len={}, pts={:?}, dts={:?}, is_corrupt={}, pixel_format={}",
-                         codec, fps, avg_fps, width, height, key_frame, raw_frame.len(),
+                         codec, fps, avg_fps, width, height, key_frame, frame.payload.len(),
                          pts, dts, corrupted, pixel_format);
 
                     let frame_processed_ts = i64::try_from(
@@ -551,7 +1609,11 @@ fn handle(params: HandleParams) -> anyhow::Result<()> {
                         avg_fps,
                         pixel_format,
                         queue_full_skipped_count,
-                        payload: raw_frame,
+                        scene_change: frame.scene_change,
+                        plane_offsets: frame.plane_offsets,
+                        plane_strides: frame.plane_strides,
+                        plane_heights: frame.plane_heights,
+                        payload: frame.payload,
                         frame_received_ts,
                         frame_processed_ts,
                         queue_len: i64::try_from(params.tx.len()).unwrap(),
@@ -595,35 +1657,43 @@ fn assign_log_level(ffmpeg_log_level: FFmpegLogLevel) -> Level {
     }
 }
 
-#[pymethods]
+#[allow(clippy::too_many_arguments)]
 impl FFMpegSource {
-    #[allow(clippy::too_many_arguments)]
-    #[new]
-    #[pyo3(signature = (uri, params,
-        queue_len = 32,
-        decode = false,
-        autoconvert_raw_formats_to_rgb24 = false,
-        block_if_queue_full = false,
-        init_timeout_ms = 10000,
-        ffmpeg_log_level = FFmpegLogLevel::Info,
-        bsf_filters = vec![])
-    )]
-    pub fn new(
+    fn start(
         uri: String,
+        source: InputSource,
         params: Vec<(String, String)>,
         queue_len: i64,
         decode: bool,
+        with_audio: bool,
         autoconvert_raw_formats_to_rgb24: bool,
         block_if_queue_full: bool,
         init_timeout_ms: u64,
         ffmpeg_log_level: FFmpegLogLevel,
         bsf_filters: Vec<BsfFilter>,
+        record_to: Option<String>,
+        segment_seconds: u64,
+        segment_format: String,
+        filter_description: Option<String>,
+        output_pixel_format: String,
+        output_width: Option<u32>,
+        output_height: Option<u32>,
+        detect_scene_changes: bool,
+        scene_change_threshold: f64,
+        scene_change_min_frames: u64,
+        annex_b_to_avc: bool,
     ) -> PyResult<Self> {
         assert!(queue_len > 0, "Queue length must be a positive number");
 
+        let output_pixel_format = parse_pixel_format(&output_pixel_format)
+            .map_err(|e| PyValueError::new_err(format!("{:?}", e)))?;
+
         let (tx, video_source) = crossbeam::channel::bounded(
             usize::try_from(queue_len).map_err(|e| PySystemError::new_err(format!("{:?}", e)))?,
         );
+        let (audio_tx, audio_source) = crossbeam::channel::bounded(
+            usize::try_from(queue_len).map_err(|e| PySystemError::new_err(format!("{:?}", e)))?,
+        );
 
         let (init_tx, init_rx) = crossbeam::channel::bounded(1);
 
@@ -631,16 +1701,30 @@ impl FFMpegSource {
         let log_level = Arc::new(Mutex::new(Some(assign_log_level(ffmpeg_log_level))));
 
         let handle_params = HandleParamsBuilder::default()
-            .uri(uri.clone())
+            .uri(uri)
+            .source(source)
             .params(params.into_iter().collect())
             .tx(tx)
+            .audio_tx(audio_tx)
             .init_complete(init_tx)
             .exit_signal(exit_signal.clone())
             .decode(decode)
+            .with_audio(with_audio)
             .autoconvert_raw_formats_to_rgb24(autoconvert_raw_formats_to_rgb24)
             .block_if_queue_full(block_if_queue_full)
             .log_level(log_level.clone())
             .bsf_filters(bsf_filters.clone())
+            .record_to(record_to)
+            .segment_seconds(segment_seconds)
+            .segment_format(segment_format)
+            .filter_description(filter_description)
+            .output_pixel_format(output_pixel_format)
+            .output_width(output_width)
+            .output_height(output_height)
+            .detect_scene_changes(detect_scene_changes)
+            .scene_change_threshold(scene_change_threshold)
+            .scene_change_min_frames(scene_change_min_frames)
+            .annex_b_to_avc(annex_b_to_avc)
             .build()
             .map_err(|e| {
                 error!("Unable to create handle params. Error is: {:?}", e);
@@ -649,7 +1733,7 @@ impl FFMpegSource {
 
         let thread = Some(spawn(move || handle_wrapper(handle_params)));
 
-        init_rx
+        let (extradata, video_codec_id) = init_rx
             .recv_timeout(std::time::Duration::from_millis(init_timeout_ms))
             .map_err(|e| {
                 error!("Unable to initialize the worker thread. Error is: {:?}", e);
@@ -658,11 +1742,178 @@ impl FFMpegSource {
 
         Ok(Self {
             video_source,
+            audio_source,
             thread,
             exit_signal,
             log_level,
+            extradata,
+            video_codec_id,
         })
     }
+}
+
+#[pymethods]
+impl FFMpegSource {
+    #[allow(clippy::too_many_arguments)]
+    #[new]
+    #[pyo3(signature = (uri, params,
+        queue_len = 32,
+        decode = false,
+        with_audio = false,
+        autoconvert_raw_formats_to_rgb24 = false,
+        block_if_queue_full = false,
+        init_timeout_ms = 10000,
+        ffmpeg_log_level = FFmpegLogLevel::Info,
+        bsf_filters = vec![],
+        record_to = None,
+        segment_seconds = 5,
+        segment_format = "mp4".to_string(),
+        filter_description = None,
+        output_pixel_format = "rgb24".to_string(),
+        output_width = None,
+        output_height = None,
+        detect_scene_changes = false,
+        scene_change_threshold = 0.15,
+        scene_change_min_frames = 5,
+        annex_b_to_avc = false)
+    )]
+    pub fn new(
+        uri: String,
+        params: Vec<(String, String)>,
+        queue_len: i64,
+        decode: bool,
+        with_audio: bool,
+        autoconvert_raw_formats_to_rgb24: bool,
+        block_if_queue_full: bool,
+        init_timeout_ms: u64,
+        ffmpeg_log_level: FFmpegLogLevel,
+        bsf_filters: Vec<BsfFilter>,
+        record_to: Option<String>,
+        segment_seconds: u64,
+        segment_format: String,
+        filter_description: Option<String>,
+        output_pixel_format: String,
+        output_width: Option<u32>,
+        output_height: Option<u32>,
+        detect_scene_changes: bool,
+        scene_change_threshold: f64,
+        scene_change_min_frames: u64,
+        annex_b_to_avc: bool,
+    ) -> PyResult<Self> {
+        Self::start(
+            uri,
+            InputSource::Uri,
+            params,
+            queue_len,
+            decode,
+            with_audio,
+            autoconvert_raw_formats_to_rgb24,
+            block_if_queue_full,
+            init_timeout_ms,
+            ffmpeg_log_level,
+            bsf_filters,
+            record_to,
+            segment_seconds,
+            segment_format,
+            filter_description,
+            output_pixel_format,
+            output_width,
+            output_height,
+            detect_scene_changes,
+            scene_change_threshold,
+            scene_change_min_frames,
+            annex_b_to_avc,
+        )
+    }
+
+    /// Build a source that reads from a Python-backed AVIO context instead of
+    /// opening `uri` by name. `read_callback(size: int) -> bytes` is called by
+    /// the worker thread whenever FFmpeg needs more input; an empty `bytes`
+    /// signals EOF. `seek_callback(offset: int, whence: int) -> int`, if
+    /// given, is called with `whence` set to `os.SEEK_SET`/`SEEK_CUR`/`SEEK_END`
+    /// or FFmpeg's `AVSEEK_SIZE` (to query the stream size) and must return the
+    /// new position (or the size, for `AVSEEK_SIZE`), or a negative number on
+    /// failure. Without a seek callback the stream is treated as unseekable.
+    #[allow(clippy::too_many_arguments)]
+    #[staticmethod]
+    #[pyo3(signature = (read_callback,
+        seek_callback = None,
+        params = vec![],
+        queue_len = 32,
+        decode = false,
+        with_audio = false,
+        autoconvert_raw_formats_to_rgb24 = false,
+        block_if_queue_full = false,
+        init_timeout_ms = 10000,
+        ffmpeg_log_level = FFmpegLogLevel::Info,
+        bsf_filters = vec![],
+        buffer_size = 4096,
+        record_to = None,
+        segment_seconds = 5,
+        segment_format = "mp4".to_string(),
+        filter_description = None,
+        output_pixel_format = "rgb24".to_string(),
+        output_width = None,
+        output_height = None,
+        detect_scene_changes = false,
+        scene_change_threshold = 0.15,
+        scene_change_min_frames = 5,
+        annex_b_to_avc = false)
+    )]
+    pub fn from_callback(
+        read_callback: Py<PyAny>,
+        seek_callback: Option<Py<PyAny>>,
+        params: Vec<(String, String)>,
+        queue_len: i64,
+        decode: bool,
+        with_audio: bool,
+        autoconvert_raw_formats_to_rgb24: bool,
+        block_if_queue_full: bool,
+        init_timeout_ms: u64,
+        ffmpeg_log_level: FFmpegLogLevel,
+        bsf_filters: Vec<BsfFilter>,
+        buffer_size: usize,
+        record_to: Option<String>,
+        segment_seconds: u64,
+        segment_format: String,
+        filter_description: Option<String>,
+        output_pixel_format: String,
+        output_width: Option<u32>,
+        output_height: Option<u32>,
+        detect_scene_changes: bool,
+        scene_change_threshold: f64,
+        scene_change_min_frames: u64,
+        annex_b_to_avc: bool,
+    ) -> PyResult<Self> {
+        Self::start(
+            "<python-callback>".to_string(),
+            InputSource::Callback {
+                read_callback,
+                seek_callback,
+                buffer_size,
+            },
+            params,
+            queue_len,
+            decode,
+            with_audio,
+            autoconvert_raw_formats_to_rgb24,
+            block_if_queue_full,
+            init_timeout_ms,
+            ffmpeg_log_level,
+            bsf_filters,
+            record_to,
+            segment_seconds,
+            segment_format,
+            filter_description,
+            output_pixel_format,
+            output_width,
+            output_height,
+            detect_scene_changes,
+            scene_change_threshold,
+            scene_change_min_frames,
+            annex_b_to_avc,
+        )
+    }
 
     pub fn stop(&self) {
         let mut exit_signal = self.exit_signal.lock();
@@ -674,6 +1925,25 @@ impl FFMpegSource {
         !*self.exit_signal.lock()
     }
 
+    /// The input video stream's codec extradata (e.g. an `avcC`/`hvcC` box,
+    /// or Annex-B SPS/PPS), as captured when the source was opened.
+    #[getter]
+    pub fn extradata(&self) -> Vec<u8> {
+        self.extradata.clone()
+    }
+
+    /// Assembles an `AVCDecoderConfigurationRecord` from `extradata`,
+    /// suitable for an MP4/MKV `avcC` box. Only H.264 is supported; returns
+    /// `None` for any other codec (including HEVC, whose structurally
+    /// different `hvcC` record this does not build), or if no SPS could be
+    /// found in the extradata.
+    pub fn avc_decoder_configuration_record(&self) -> Option<Vec<u8>> {
+        if self.video_codec_id != Id::H264 {
+            return None;
+        }
+        build_avc_decoder_configuration_record(&self.extradata)
+    }
+
     #[pyo3(signature = (timeout_ms = 10000))]
     pub fn video_frame(&self, timeout_ms: usize) -> PyResult<VideoFrameEnvelope> {
         if *self.exit_signal.lock() {
@@ -697,6 +1967,29 @@ impl FFMpegSource {
         })
     }
 
+    #[pyo3(signature = (timeout_ms = 10000))]
+    pub fn audio_frame(&self, timeout_ms: usize) -> PyResult<AudioFrameEnvelope> {
+        if *self.exit_signal.lock() {
+            return Err(PySystemError::new_err("Worker thread is not running"));
+        }
+        Python::with_gil(|py| {
+            py.allow_threads(|| {
+                let res = self
+                    .audio_source
+                    .recv_timeout(std::time::Duration::from_millis(
+                        u64::try_from(timeout_ms).map_err(|e| {
+                            error!("Unable to convert timeout to u64. Error is: {:?}", e);
+                            e
+                        })?,
+                    ));
+                match res {
+                    Err(e) => Err(PyBrokenPipeError::new_err(format!("{:?}", e))),
+                    Ok(x) => Ok(x),
+                }
+            })
+        })
+    }
+
     #[setter]
     pub fn log_level(&self, ffmpeg_log_level: FFmpegLogLevel) {
         let mut ll = self.log_level.lock();
@@ -710,6 +2003,7 @@ fn ffmpeg_input(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
         log::warn!("Unable to initialize logger. Error is: {:?}", e);
     });
     m.add_class::<VideoFrameEnvelope>()?;
+    m.add_class::<AudioFrameEnvelope>()?;
     m.add_class::<FFMpegSource>()?;
     m.add_class::<FFmpegLogLevel>()?;
     m.add_class::<BsfFilter>()?;